@@ -2,8 +2,10 @@ use std::io::prelude::*;
 use std::error::Error;
 use std::env;
 use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::net::TcpStream;
+use serde::Deserialize;
 
 pub struct Client {
     server_addr: String,
@@ -151,6 +153,55 @@ impl ClientConfig {
             outpath: outpath,
         });
     }
+
+    pub fn from_file(path: &str) -> Result<ClientConfig, ClientConfigError> {
+        let raw = match fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(v) => return Err(ClientConfigError::new(format!(
+                "failed to read config file {}: {}", path, v))),
+        };
+        let version: ConfigVersion = match toml::from_str(&raw) {
+            Ok(v) => v,
+            Err(v) => return Err(ClientConfigError::new(format!(
+                "failed to parse config version: {}", v))),
+        };
+        let file: ClientConfigFileV1 = match version.version.as_str() {
+            CLIENT_CONFIG_VERSION => match toml::from_str(&raw) {
+                Ok(v) => v,
+                Err(v) => return Err(ClientConfigError::new(format!(
+                    "failed to parse config file {}: {}", path, v))),
+            },
+            v => return Err(ClientConfigError::new(format!(
+                "unsupported config version: {}", v))),
+        };
+        return Ok(ClientConfig {
+            server_addr: file.client.server_addr,
+            inpath: file.client.inpath,
+            outpath: file.client.outpath,
+        });
+    }
+}
+
+// CLIENT_CONFIG_VERSION is the only top-level `version` this loader accepts today.
+// Bumping the on-disk format means adding a new ClientConfigFileVN struct,
+// a new match arm here, and a migration from the previous version's fields.
+const CLIENT_CONFIG_VERSION: &str = "1";
+
+#[derive(Debug, Deserialize)]
+struct ConfigVersion {
+    version: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientConfigFileV1 {
+    client: ClientConfigFileClientV1,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientConfigFileClientV1 {
+    server_addr: String,
+    inpath: String,
+    outpath: String,
 }
 
 #[derive(Debug)]
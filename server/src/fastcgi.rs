@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use crate::request::{read_exact_with_deadline, RequestError};
+
+const FCGI_VERSION_1: u8 = 1;
+const FCGI_BEGIN_REQUEST: u8 = 1;
+const FCGI_END_REQUEST: u8 = 3;
+const FCGI_PARAMS: u8 = 4;
+const FCGI_STDIN: u8 = 5;
+const FCGI_STDOUT: u8 = 6;
+const FCGI_REQUEST_COMPLETE: u8 = 0;
+
+pub(crate) struct FastCgiRequest {
+    pub(crate) request_id: u16,
+    pub(crate) method: String,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: String,
+}
+
+struct RecordHeader {
+    kind: u8,
+    request_id: u16,
+    content_length: u16,
+    padding_length: u8,
+}
+
+// read_request decodes a single FastCGI request: the mandatory
+// BEGIN_REQUEST record, a run of PARAMS records terminated by an empty
+// one, and a run of STDIN records terminated by an empty one. CGI
+// params are translated into the same header shape `parse_calculation`
+// already knows how to read (HTTP_* params lowercased, dashed, and
+// stripped of the prefix; CONTENT_TYPE passed through directly).
+//
+// `idle_timeout`/`request_timeout` bound every read the same way
+// `request::read_request` bounds the HTTP path, sharing one deadline
+// across the whole request: a stalled client or upstream can't hold the
+// connection (and the `Semaphore` permit guarding it in `Server::start`)
+// open forever.
+pub(crate) async fn read_request(
+    conn: &mut TcpStream,
+    idle_timeout: Duration,
+    request_timeout: Duration,
+) -> Result<FastCgiRequest, FastCgiError> {
+    let mut deadline: Option<Instant> = None;
+    let begin = read_record_header(conn, &mut deadline, idle_timeout, request_timeout).await?;
+    if begin.kind != FCGI_BEGIN_REQUEST {
+        return Err(FastCgiError::new(format!(
+            "expected BEGIN_REQUEST, got record type {}", begin.kind)));
+    }
+    read_record_body(conn, &begin, &mut deadline, idle_timeout, request_timeout).await?;
+    let request_id = begin.request_id;
+
+    let mut params_raw = Vec::new();
+    loop {
+        let header = read_record_header(conn, &mut deadline, idle_timeout, request_timeout).await?;
+        if header.kind != FCGI_PARAMS {
+            return Err(FastCgiError::new(format!(
+                "expected PARAMS, got record type {}", header.kind)));
+        }
+        let content = read_record_body(conn, &header, &mut deadline, idle_timeout, request_timeout).await?;
+        if content.is_empty() {
+            break;
+        }
+        params_raw.extend_from_slice(&content);
+    }
+    let params = decode_params(&params_raw)?;
+
+    let mut body = Vec::new();
+    loop {
+        let header = read_record_header(conn, &mut deadline, idle_timeout, request_timeout).await?;
+        if header.kind != FCGI_STDIN {
+            return Err(FastCgiError::new(format!(
+                "expected STDIN, got record type {}", header.kind)));
+        }
+        let content = read_record_body(conn, &header, &mut deadline, idle_timeout, request_timeout).await?;
+        if content.is_empty() {
+            break;
+        }
+        body.extend_from_slice(&content);
+    }
+
+    let mut headers = HashMap::new();
+    for (k, v) in &params {
+        if let Some(name) = k.strip_prefix("HTTP_") {
+            headers.insert(name.to_lowercase().replace('_', "-"), v.clone());
+        }
+    }
+    if let Some(v) = params.get("CONTENT_TYPE") {
+        headers.insert(String::from("content-type"), v.clone());
+    }
+    let method = params.get("REQUEST_METHOD").cloned().unwrap_or_default();
+
+    return Ok(FastCgiRequest {
+        request_id: request_id,
+        method: method,
+        headers: headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    });
+}
+
+async fn read_record_header(
+    conn: &mut TcpStream,
+    deadline: &mut Option<Instant>,
+    idle_timeout: Duration,
+    request_timeout: Duration,
+) -> Result<RecordHeader, FastCgiError> {
+    let mut buf = [0u8; 8];
+    read_exact_with_deadline(conn, &mut buf, deadline, idle_timeout, request_timeout).await?;
+    return Ok(RecordHeader {
+        kind: buf[1],
+        request_id: ((buf[2] as u16) << 8) | buf[3] as u16,
+        content_length: ((buf[4] as u16) << 8) | buf[5] as u16,
+        padding_length: buf[6],
+    });
+}
+
+async fn read_record_body(
+    conn: &mut TcpStream,
+    header: &RecordHeader,
+    deadline: &mut Option<Instant>,
+    idle_timeout: Duration,
+    request_timeout: Duration,
+) -> Result<Vec<u8>, FastCgiError> {
+    let mut content = vec![0u8; header.content_length as usize];
+    read_exact_with_deadline(conn, &mut content, deadline, idle_timeout, request_timeout).await?;
+    if header.padding_length > 0 {
+        let mut padding = vec![0u8; header.padding_length as usize];
+        read_exact_with_deadline(conn, &mut padding, deadline, idle_timeout, request_timeout).await?;
+    }
+    return Ok(content);
+}
+
+// decode_params parses the FastCGI name-value pair encoding used by the
+// PARAMS stream: each of name and value is preceded by a length that is
+// either a single byte (top bit clear) or a 4-byte big-endian length
+// (top bit set on the first byte, cleared before use).
+fn decode_params(raw: &[u8]) -> Result<HashMap<String, String>, FastCgiError> {
+    let mut params = HashMap::new();
+    let mut i = 0;
+    while i < raw.len() {
+        let (name_len, n) = read_length(raw, i)?;
+        i += n;
+        let (value_len, n) = read_length(raw, i)?;
+        i += n;
+        let name_end = i + name_len;
+        let value_end = name_end + value_len;
+        if value_end > raw.len() {
+            return Err(FastCgiError::new(String::from("truncated PARAMS record")));
+        }
+        let name = String::from_utf8_lossy(&raw[i..name_end]).to_string();
+        let value = String::from_utf8_lossy(&raw[name_end..value_end]).to_string();
+        params.insert(name, value);
+        i = value_end;
+    }
+    return Ok(params);
+}
+
+fn read_length(raw: &[u8], i: usize) -> Result<(usize, usize), FastCgiError> {
+    if i >= raw.len() {
+        return Err(FastCgiError::new(String::from("truncated PARAMS length")));
+    }
+    if raw[i] & 0x80 == 0 {
+        return Ok((raw[i] as usize, 1));
+    }
+    if i + 3 >= raw.len() {
+        return Err(FastCgiError::new(String::from("truncated PARAMS length")));
+    }
+    let len = (((raw[i] & 0x7f) as usize) << 24)
+        | ((raw[i + 1] as usize) << 16)
+        | ((raw[i + 2] as usize) << 8)
+        | (raw[i + 3] as usize);
+    return Ok((len, 4));
+}
+
+// write_stdout writes `body` as one or more STDOUT records (chunked to the
+// protocol's 16-bit content length), the empty STDOUT record that ends the
+// stream, and a final END_REQUEST record.
+pub(crate) async fn write_stdout(conn: &mut TcpStream, request_id: u16, body: &[u8]) -> Result<(), FastCgiError> {
+    for chunk in body.chunks(0xffff) {
+        write_record(conn, FCGI_STDOUT, request_id, chunk).await?;
+    }
+    write_record(conn, FCGI_STDOUT, request_id, &[]).await?;
+    write_record(conn, FCGI_END_REQUEST, request_id, &END_REQUEST_BODY).await?;
+    if let Err(v) = conn.flush().await {
+        return Err(FastCgiError::new(v.to_string()));
+    }
+    return Ok(());
+}
+
+// appStatus=0 (4 bytes), protocolStatus=FCGI_REQUEST_COMPLETE, reserved (3 bytes).
+const END_REQUEST_BODY: [u8; 8] = [0, 0, 0, 0, FCGI_REQUEST_COMPLETE, 0, 0, 0];
+
+async fn write_record(conn: &mut TcpStream, kind: u8, request_id: u16, content: &[u8]) -> Result<(), FastCgiError> {
+    let mut record = Vec::with_capacity(8 + content.len());
+    record.push(FCGI_VERSION_1);
+    record.push(kind);
+    record.push((request_id >> 8) as u8);
+    record.push((request_id & 0xff) as u8);
+    record.push((content.len() >> 8) as u8);
+    record.push((content.len() & 0xff) as u8);
+    record.push(0); // padding_length
+    record.push(0); // reserved
+    record.extend_from_slice(content);
+    if let Err(v) = conn.write_all(&record).await {
+        return Err(FastCgiError::new(v.to_string()));
+    }
+    return Ok(());
+}
+
+#[derive(Debug)]
+pub(crate) struct FastCgiError {
+    msg: String,
+}
+
+impl FastCgiError {
+    pub(crate) fn new(msg: String) -> FastCgiError {
+        return FastCgiError { msg };
+    }
+}
+
+impl fmt::Display for FastCgiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "failed to handle fastcgi request: {}", self.msg);
+    }
+}
+
+impl Error for FastCgiError {
+    fn description(&self) -> &str {
+        return &self.msg;
+    }
+}
+
+impl From<RequestError> for FastCgiError {
+    fn from(err: RequestError) -> FastCgiError {
+        return match err {
+            RequestError::Parse(v) => FastCgiError::new(v.to_string()),
+            RequestError::Timeout => FastCgiError::new(String::from("timed out waiting on client")),
+            RequestError::Closed => FastCgiError::new(String::from("connection closed before a request was received")),
+        };
+    }
+}
@@ -1,82 +1,295 @@
-use std::io::prelude::*;
+use std::collections::HashMap;
 use std::error::Error;
 use std::env;
 use std::fmt;
-use std::net::{TcpListener, TcpStream};
-use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use uuid::Uuid;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
 use regex::Regex;
 
+mod fastcgi;
+mod request;
+
+// Protocol selects the listener behavior in `Server::start`: plain HTTP/1.1,
+// or FastCGI for deployment behind a web server (nginx/Apache) that handles
+// TLS, routing, and static files and proxies application requests to us.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Http,
+    FastCgi,
+}
+
 pub struct Server {
     addr: String,
-    pool: WorkerPool,
+    pool_size: usize,
+    protocol: Protocol,
+    client_timeout: Duration,
+    client_disconnect: Duration,
 }
 
 impl Server {
     pub fn new(cfg: ServerConfig) -> Result<Server, ServerError> {
-        let pool = match WorkerPool::new(cfg.pool_size) {
-            Ok(v) => v,
-            Err(v) => return Err(ServerError::new(v.to_string()))
-        };
         return Ok(Server {
             addr: format!("{}:{}", cfg.host, cfg.port),
-            pool: pool,
+            pool_size: cfg.pool_size,
+            protocol: cfg.protocol,
+            client_timeout: cfg.client_timeout,
+            client_disconnect: cfg.client_disconnect,
         });
     }
 
-    pub fn start(&mut self) -> Result<(), ServerError> {
-        let listener = match TcpListener::bind(self.addr.clone()) {
+    // start accepts connections forever, spawning each onto its own task
+    // rather than pinning an OS thread per connection. `pool_size` is
+    // enforced as the max number of connections handled concurrently via a
+    // semaphore instead of a fixed-size worker thread pool.
+    pub async fn start(&mut self) -> Result<(), ServerError> {
+        let listener = match TcpListener::bind(&self.addr).await {
             Ok(v) => v,
             Err(v) => return Err(ServerError::new(format!(
                 "failed to serve on {}: {}", self.addr, v)))
         };
-        for conn in listener.incoming() {
-            let conn = match conn {
+        let permits = Arc::new(Semaphore::new(self.pool_size));
+        loop {
+            let (conn, _) = match listener.accept().await {
                 Ok(v) => v,
                 Err(v) => return Err(ServerError::new(format!("connection error: {}", v)))
             };
-            let job = Job::new(Box::new(|| { handle(conn) }));
-            if let Err(e) = self.pool.execute(job) {
-                return Err(ServerError::new(e.to_string()));
-            }
+            let permit = match Arc::clone(&permits).acquire_owned().await {
+                Ok(v) => v,
+                Err(v) => return Err(ServerError::new(v.to_string())),
+            };
+            let protocol = self.protocol;
+            let client_timeout = self.client_timeout;
+            let client_disconnect = self.client_disconnect;
+            tokio::spawn(async move {
+                match protocol {
+                    Protocol::Http => handle(conn, client_timeout, client_disconnect).await,
+                    Protocol::FastCgi => handle_fastcgi(conn, client_timeout, client_disconnect).await,
+                }
+                drop(permit);
+            });
         }
-        return Ok(());
     }
 }
 
-fn handle(mut conn: TcpStream) {
-    const POST_PREFIX: &str = "POST / HTTP/1.1";
-    const BAD_REQ_RESP: &str = "HTTP/1.1 400 BAD REQUEST\r\n";
-
-    let mut buf = [0; 1024];
-    let n = conn.read(&mut buf).unwrap();
-    let req = String::from_utf8_lossy(&buf[..n]);
-
-    let resp: String;
-    if !req.starts_with(POST_PREFIX) {
-        resp = String::from(BAD_REQ_RESP);
-    } else {
-        let r = Regex::new(r"(?s)operator=(.*)&operands=(.*)").unwrap();
-        let c = r.captures(req.trim()).unwrap();
-        let operator = c.get(1).map_or("", |v| v.as_str());
-        let operands = c.get(2).map_or("", |v| v.as_str());
-        let calc = Calculation::parse(operator, operands).unwrap();
-        let result: Option<f64> = match calc.operator {
-            Operator::Add => calc.operands.into_iter().reduce(|a, b| a + b),
-            Operator::Sub => calc.operands.into_iter().reduce(|a, b| a - b),
-            Operator::Mul => calc.operands.into_iter().reduce(|a, b| a * b),
-            Operator::Div => calc.operands.into_iter().reduce(|a, b| a / b),
-            Operator::Rem => calc.operands.into_iter().reduce(|a, b| a % b),
+async fn handle(mut conn: TcpStream, client_timeout: Duration, client_disconnect: Duration) {
+    const BAD_REQ_RESP: &str = "HTTP/1.1 400 BAD REQUEST\r\nConnection: close\r\n\r\n";
+    const TIMEOUT_RESP: &str = "HTTP/1.1 408 REQUEST TIMEOUT\r\nConnection: close\r\n\r\n";
+
+    let mut carry = Vec::new();
+    loop {
+        let req = match request::read_request(&mut conn, client_disconnect, client_timeout, &mut carry).await {
+            Ok(v) => v,
+            // The peer closed the connection before sending a new request:
+            // the normal way a keep-alive connection ends. Nothing to
+            // write back to.
+            Err(request::RequestError::Closed) => return,
+            Err(request::RequestError::Timeout) => {
+                let _ = write_resp(&mut conn, TIMEOUT_RESP).await;
+                return;
+            },
+            Err(request::RequestError::Parse(_)) => {
+                let _ = write_resp(&mut conn, BAD_REQ_RESP).await;
+                return;
+            },
+        };
+        if req.method != "POST" || req.path != "/" {
+            let _ = write_resp(&mut conn, BAD_REQ_RESP).await;
+            return;
+        }
+
+        let keep_alive = req.keep_alive();
+        let accept_json = accepts_json(&req.headers);
+
+        let calc = match parse_calculation(&req.headers, &req.body) {
+            Ok(v) => v,
+            Err(e) => {
+                let _ = write_resp(&mut conn, &error_resp(accept_json, e.to_string())).await;
+                return;
+            },
         };
-        resp = match result {
-            Some(v) => v.to_string(),
-            None => String::from("nan"),
+        let resp = match calc.evaluate() {
+            Ok(Some(v)) => ok_resp(accept_json, keep_alive, v),
+            Ok(None) => nan_resp(accept_json, keep_alive),
+            Err(e) => {
+                let _ = write_resp(&mut conn, &error_resp(accept_json, e.to_string())).await;
+                return;
+            },
         };
+        if write_resp(&mut conn, &resp).await.is_err() {
+            return;
+        }
+        if !keep_alive {
+            return;
+        }
+    }
+}
+
+// parse_calculation dispatches on the request's Content-Type: a JSON body
+// deserializes straight into operator/operands, while anything else is
+// treated as the legacy application/x-www-form-urlencoded payload. It
+// takes headers/body directly rather than `request::HttpRequest` so both
+// the HTTP and FastCGI handlers can share it.
+fn parse_calculation(headers: &HashMap<String, String>, body: &str) -> Result<Calculation, CalculationParseError> {
+    if is_json_content(headers) {
+        let json: CalculationRequestJson = match serde_json::from_str(body) {
+            Ok(v) => v,
+            Err(v) => return Err(CalculationParseError::new(format!("invalid json body: {}", v))),
+        };
+        let operator = match Operator::parse(&json.operator) {
+            Ok(v) => v,
+            Err(v) => return Err(v),
+        };
+        return Ok(Calculation::from_operands(operator, json.operands));
+    }
+
+    let r = Regex::new(r"(?s)operator=(.*)&operands=([^&]*)(?:&mode=(.*))?").unwrap();
+    let c = match r.captures(body.trim()) {
+        Some(v) => v,
+        None => return Err(CalculationParseError::new(String::from("malformed form body"))),
+    };
+    let operator = c.get(1).map_or("", |v| v.as_str());
+    let operands = c.get(2).map_or("", |v| v.as_str());
+    let mode = c.get(3).map_or("", |v| v.as_str());
+    return Calculation::parse(operator, operands, mode);
+}
+
+fn is_json_content(headers: &HashMap<String, String>) -> bool {
+    return headers.get("content-type")
+        .map_or(false, |v| v.to_lowercase().contains("application/json"));
+}
+
+fn accepts_json(headers: &HashMap<String, String>) -> bool {
+    return headers.get("accept")
+        .map_or(false, |v| v.to_lowercase().contains("application/json"));
+}
+
+fn ok_resp(accept_json: bool, keep_alive: bool, result: f64) -> String {
+    if accept_json {
+        let body = serde_json::to_string(&CalculationResultResponse { result: result }).unwrap();
+        return json_ok_resp(keep_alive, body);
+    }
+    return plain_ok_resp(keep_alive, result.to_string());
+}
+
+// nan_resp is the `ok_resp` analogue for a flat calculation with no
+// operands to reduce over (see `Calculation::evaluate`): still a 200, but
+// with no numeric result, honoring content negotiation the same way.
+fn nan_resp(accept_json: bool, keep_alive: bool) -> String {
+    if accept_json {
+        let body = serde_json::to_string(&CalculationResultResponse { result: f64::NAN }).unwrap();
+        return json_ok_resp(keep_alive, body);
+    }
+    return plain_ok_resp(keep_alive, String::from("nan"));
+}
+
+fn json_ok_resp(keep_alive: bool, body: String) -> String {
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    return format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n{}",
+        body.len(), connection, body,
+    );
+}
+
+fn plain_ok_resp(keep_alive: bool, body: String) -> String {
+    return format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n{}",
+        body.len(),
+        if keep_alive { "keep-alive" } else { "close" },
+        body,
+    );
+}
+
+fn error_resp(accept_json: bool, msg: String) -> String {
+    if accept_json {
+        let body = serde_json::to_string(&CalculationErrorResponse { error: msg }).unwrap();
+        return format!(
+            "HTTP/1.1 400 BAD REQUEST\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(), body,
+        );
     }
-    conn.write(resp.as_bytes()).unwrap();
-    conn.flush().unwrap();
+    return String::from("HTTP/1.1 400 BAD REQUEST\r\nConnection: close\r\n\r\n");
+}
+
+// write_resp writes a full response and flushes it. Errors are returned
+// rather than unwrapped: the peer may have already closed the connection
+// (e.g. reset instead of a graceful FIN), and that's an ordinary way for a
+// keep-alive connection to end, not something a connection task should
+// panic over.
+async fn write_resp(conn: &mut TcpStream, resp: &str) -> std::io::Result<()> {
+    conn.write_all(resp.as_bytes()).await?;
+    conn.flush().await?;
+    return Ok(());
+}
+
+// handle_fastcgi serves a single FastCGI request on `conn`. A FastCGI
+// connection carries exactly one request, unlike the HTTP handler's
+// keep-alive loop. It shares `parse_calculation`/`Calculation::evaluate`
+// with the HTTP path and reports errors the same way a CGI responder would,
+// via a "Status:" line in the STDOUT body rather than an HTTP status line.
+// `client_timeout`/`client_disconnect` bound the read the same way they
+// bound `handle`'s, so a stalled peer can't pin the `Semaphore` permit
+// `Server::start` holds for the life of this call.
+async fn handle_fastcgi(mut conn: TcpStream, client_timeout: Duration, client_disconnect: Duration) {
+    let req = match fastcgi::read_request(&mut conn, client_disconnect, client_timeout).await {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    if req.method != "POST" {
+        let body = b"Status: 400 Bad Request\r\n\r\n";
+        let _ = fastcgi::write_stdout(&mut conn, req.request_id, body).await;
+        return;
+    }
+
+    let accept_json = accepts_json(&req.headers);
+    let calc = match parse_calculation(&req.headers, &req.body) {
+        Ok(v) => v,
+        Err(e) => {
+            let body = fastcgi_error_body(accept_json, e.to_string());
+            let _ = fastcgi::write_stdout(&mut conn, req.request_id, body.as_bytes()).await;
+            return;
+        },
+    };
+    let body = match calc.evaluate() {
+        Ok(Some(v)) => fastcgi_ok_body(accept_json, v),
+        Ok(None) => fastcgi_nan_body(accept_json),
+        Err(e) => fastcgi_error_body(accept_json, e.to_string()),
+    };
+    let _ = fastcgi::write_stdout(&mut conn, req.request_id, body.as_bytes()).await;
+}
+
+fn fastcgi_ok_body(accept_json: bool, result: f64) -> String {
+    if accept_json {
+        let body = serde_json::to_string(&CalculationResultResponse { result: result }).unwrap();
+        return format!("Content-Type: application/json\r\n\r\n{}", body);
+    }
+    return fastcgi_plain_body(result.to_string());
+}
+
+// fastcgi_nan_body is the `fastcgi_ok_body` analogue for a flat calculation
+// with no operands to reduce over, honoring content negotiation the same way.
+fn fastcgi_nan_body(accept_json: bool) -> String {
+    if accept_json {
+        let body = serde_json::to_string(&CalculationResultResponse { result: f64::NAN }).unwrap();
+        return format!("Content-Type: application/json\r\n\r\n{}", body);
+    }
+    return fastcgi_plain_body(String::from("nan"));
+}
+
+fn fastcgi_plain_body(result: String) -> String {
+    return format!("Content-Type: text/plain\r\n\r\n{}", result);
+}
+
+fn fastcgi_error_body(accept_json: bool, msg: String) -> String {
+    if accept_json {
+        let body = serde_json::to_string(&CalculationErrorResponse { error: msg }).unwrap();
+        return format!("Status: 400 Bad Request\r\nContent-Type: application/json\r\n\r\n{}", body);
+    }
+    return String::from("Status: 400 Bad Request\r\n\r\n");
 }
 
 #[derive(Debug)]
@@ -102,14 +315,24 @@ impl Error for ServerError {
     }
 }
 
+const DEFAULT_CLIENT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_CLIENT_DISCONNECT_SECS: u64 = 60;
+
 pub struct ServerConfig {
     pub host: String,
     pub port: String,
     pub pool_size: usize,
+    pub protocol: Protocol,
+    pub client_timeout: Duration,
+    pub client_disconnect: Duration,
 }
 
 impl ServerConfig {
-    pub fn new(mut args: env::Args) -> Result<ServerConfig, ServerConfigError> {
+    // new accepts anything yielding owned argument strings, not just
+    // `env::Args` directly, so callers that need to buffer or peek
+    // arguments first (`env::Args` isn't `Clone`) can still hand in the
+    // positional `host port pool_size` form.
+    pub fn new<I: Iterator<Item = String>>(mut args: I) -> Result<ServerConfig, ServerConfigError> {
         args.next();
         let host = match args.next() {
             Some(v) => v,
@@ -140,172 +363,123 @@ impl ServerConfig {
             host: host,
             port: port,
             pool_size: size,
+            protocol: Protocol::Http,
+            client_timeout: Duration::from_secs(DEFAULT_CLIENT_TIMEOUT_SECS),
+            client_disconnect: Duration::from_secs(DEFAULT_CLIENT_DISCONNECT_SECS),
         });
     }
-}
 
-#[derive(Debug)]
-pub struct ServerConfigError {
-    msg: String,
-}
-
-impl ServerConfigError {
-    pub fn new(msg: String) -> ServerConfigError {
-        return ServerConfigError { msg };
-    }
-}
-
-impl fmt::Display for ServerConfigError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return write!(f, "failed to initialize server config: {}", self.msg);
-    }
-}
-
-impl Error for ServerConfigError {
-    fn description(&self) -> &str {
-        return &self.msg;
-    }
-}
-
-struct Job {
-    id: Uuid,
-    do_fn: Box<dyn FnOnce() + Send + 'static>,
-}
-
-impl Job {
-    fn new(do_fn: Box<dyn FnOnce() + Send + 'static>) -> Job {
-        return Job {
-            id: Uuid::new_v4(),
-            do_fn: do_fn,
+    pub fn from_file(path: &str) -> Result<ServerConfig, ServerConfigError> {
+        let raw = match fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(v) => return Err(ServerConfigError::new(format!(
+                "failed to read config file {}: {}", path, v))),
         };
-    }
-}
-
-enum JobMessage {
-    NewJob(Job),
-    Terminate,
-}
-
-const WORKER_POOL_MIN_SIZE: usize = 1;
-const WORKER_POOL_MAX_SIZE: usize = 16;
-
-struct WorkerPool {
-    workers: Vec<Worker>,
-    job_send: mpsc::Sender<JobMessage>,
-}
-
-impl WorkerPool {
-    fn new(size: usize) -> Result<WorkerPool, WorkerPoolNewError> {
-        let (send, recv) = mpsc::channel();
-        let recv = Arc::new(Mutex::new(recv));
-        let mut pool = WorkerPool {
-            workers: Vec::with_capacity(size),
-            job_send: send,
+        let version: ConfigVersion = match toml::from_str(&raw) {
+            Ok(v) => v,
+            Err(v) => return Err(ServerConfigError::new(format!(
+                "failed to parse config version: {}", v))),
         };
-        for i in 0..size {
-            let w = match Worker::new(i as u16, Arc::clone(&recv)) {
-                Ok(w) => w,
-                Err(w) => return Err(WorkerPoolNewError::new(w.to_string())),
-            };
-            pool.workers.push(w);
-        }
-        return Ok(pool);
-    }
-
-    fn execute(&self, job: Job) -> Result<(), WorkerPoolExecuteError> {
-        let job_id = job.id;
-        if let Err(e) = self.job_send.send(JobMessage::NewJob(job)) {
-            return Err(WorkerPoolExecuteError::new(format!(
-                "job_id={} err={}", job_id, e.to_string())));
+        let file: ServerConfigFileV1 = match version.version.as_str() {
+            SERVER_CONFIG_VERSION => match toml::from_str(&raw) {
+                Ok(v) => v,
+                Err(v) => return Err(ServerConfigError::new(format!(
+                    "failed to parse config file {}: {}", path, v))),
+            },
+            v => return Err(ServerConfigError::new(format!(
+                "unsupported config version: {}", v))),
+        };
+        let size = file.worker_pool.size;
+        if size < WORKER_POOL_MIN_SIZE || size > WORKER_POOL_MAX_SIZE {
+            return Err(ServerConfigError::new(format!(
+                "invalid pool size: min={} max={} got={}",
+                WORKER_POOL_MIN_SIZE, WORKER_POOL_MAX_SIZE, size
+            )));
         }
-        return Ok(());
+        let client_timeout = file.server.client_timeout_secs
+            .map_or(Duration::from_secs(DEFAULT_CLIENT_TIMEOUT_SECS), Duration::from_secs);
+        let client_disconnect = file.server.client_disconnect_secs
+            .map_or(Duration::from_secs(DEFAULT_CLIENT_DISCONNECT_SECS), Duration::from_secs);
+        let protocol = match file.server.protocol {
+            Some(v) => match v.trim().to_lowercase().as_str() {
+                PROTOCOL_HTTP => Protocol::Http,
+                PROTOCOL_FASTCGI => Protocol::FastCgi,
+                v => return Err(ServerConfigError::new(format!("unknown protocol: {}", v))),
+            },
+            None => Protocol::Http,
+        };
+        return Ok(ServerConfig {
+            host: file.server.host,
+            port: file.server.port,
+            pool_size: size,
+            protocol: protocol,
+            client_timeout: client_timeout,
+            client_disconnect: client_disconnect,
+        });
     }
 }
 
-impl Drop for WorkerPool {
-    fn drop(&mut self) {
-        for _ in &mut self.workers {
-            self.job_send.send(JobMessage::Terminate).unwrap();    
-        }
-        for w in &mut self.workers {
-            if let Some(thread) = w.thread.take() {
-                thread.join().unwrap();
-            }
-        }
-    }
-}
+const PROTOCOL_HTTP: &str = "http";
+const PROTOCOL_FASTCGI: &str = "fastcgi";
 
-#[derive(Debug)]
-pub struct WorkerPoolNewError {
-    msg: String,
+// SERVER_CONFIG_VERSION is the only top-level `version` this loader accepts today.
+// Bumping the on-disk format means adding a new ServerConfigFileVN struct,
+// a new match arm here, and a migration from the previous version's fields.
+const SERVER_CONFIG_VERSION: &str = "1";
+
+#[derive(Debug, Deserialize)]
+struct ConfigVersion {
+    version: String,
 }
 
-impl WorkerPoolNewError {
-    pub fn new(msg: String) -> WorkerPoolNewError {
-        return WorkerPoolNewError { msg };
-    }
+#[derive(Debug, Deserialize)]
+struct ServerConfigFileV1 {
+    server: ServerConfigFileServerV1,
+    worker_pool: ServerConfigFileWorkerPoolV1,
 }
 
-impl fmt::Display for WorkerPoolNewError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return write!(f, "failed to initialize worker pool: {}", self.msg);
-    }
+#[derive(Debug, Deserialize)]
+struct ServerConfigFileServerV1 {
+    host: String,
+    port: String,
+    protocol: Option<String>,
+    client_timeout_secs: Option<u64>,
+    client_disconnect_secs: Option<u64>,
 }
 
-impl Error for WorkerPoolNewError {
-    fn description(&self) -> &str {
-        return &self.msg;
-    }
+#[derive(Debug, Deserialize)]
+struct ServerConfigFileWorkerPoolV1 {
+    size: usize,
 }
 
 #[derive(Debug)]
-pub struct WorkerPoolExecuteError {
+pub struct ServerConfigError {
     msg: String,
 }
 
-impl WorkerPoolExecuteError {
-    pub fn new(msg: String) -> WorkerPoolExecuteError {
-        return WorkerPoolExecuteError { msg };
+impl ServerConfigError {
+    pub fn new(msg: String) -> ServerConfigError {
+        return ServerConfigError { msg };
     }
 }
 
-impl fmt::Display for WorkerPoolExecuteError {
+impl fmt::Display for ServerConfigError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return write!(f, "failed to execute job: {}", self.msg);
+        return write!(f, "failed to initialize server config: {}", self.msg);
     }
 }
 
-impl Error for WorkerPoolExecuteError {
+impl Error for ServerConfigError {
     fn description(&self) -> &str {
         return &self.msg;
     }
 }
 
-struct Worker {
-    id: u16,
-    thread: Option<thread::JoinHandle<()>>,
-}
-
-impl Worker {
-    fn new(id: u16, job_recv: Arc<Mutex<mpsc::Receiver<JobMessage>>>) -> Result<Worker, Box<dyn Error>> {
-        let t = thread::Builder::new().spawn(move || {
-            loop {
-                let job_msg = job_recv.lock().unwrap().recv().unwrap();
-                match job_msg {
-                    JobMessage::NewJob(job) => {
-                        println!("new job received: worker_id={} job_id={}", id, job.id);
-                        (job.do_fn)();
-                    }, 
-                    JobMessage::Terminate => {
-                        println!("terminate received: worker_id={}", id);
-                        break;
-                    },
-                }
-            }
-        })?;
-        return Ok(Worker { id: id, thread: Some(t) });
-    }
-}
+// WORKER_POOL_{MIN,MAX}_SIZE still bound `pool_size`: it now caps the
+// number of connections `Server::start` handles concurrently via a
+// `tokio::sync::Semaphore` rather than a fixed number of OS threads.
+const WORKER_POOL_MIN_SIZE: usize = 1;
+const WORKER_POOL_MAX_SIZE: usize = 16;
 
 const OPERATOR_ADD: &str = "add";
 const OPERATOR_SUB: &str = "sub";
@@ -322,20 +496,82 @@ enum Operator {
     Rem,
 }
 
+impl Operator {
+    fn parse(s: &str) -> Result<Operator, CalculationParseError> {
+        return match s.trim().to_lowercase().as_str() {
+            OPERATOR_ADD => Ok(Operator::Add),
+            OPERATOR_SUB => Ok(Operator::Sub),
+            OPERATOR_MUL => Ok(Operator::Mul),
+            OPERATOR_DIV => Ok(Operator::Div),
+            OPERATOR_REM => Ok(Operator::Rem),
+            _ => Err(CalculationParseError::new(format!("unknown operator: {}", s))),
+        };
+    }
+
+    fn apply(&self, a: f64, b: f64) -> f64 {
+        return match self {
+            Operator::Add => a + b,
+            Operator::Sub => a - b,
+            Operator::Mul => a * b,
+            Operator::Div => a / b,
+            Operator::Rem => a % b,
+        };
+    }
+}
+
+const MODE_FLAT: &str = "flat";
+const MODE_POSTFIX: &str = "postfix";
+
+enum PostfixToken {
+    Operand(f64),
+    Operator(Operator),
+}
+
+enum CalculationMode {
+    Flat { operator: Operator, operands: Vec<f64> },
+    Postfix { tokens: Vec<PostfixToken> },
+}
+
 struct Calculation {
-    operator: Operator,
+    mode: CalculationMode,
+}
+
+#[derive(Deserialize)]
+struct CalculationRequestJson {
+    operator: String,
     operands: Vec<f64>,
 }
 
+#[derive(Serialize)]
+struct CalculationResultResponse {
+    result: f64,
+}
+
+#[derive(Serialize)]
+struct CalculationErrorResponse {
+    error: String,
+}
+
 impl Calculation {
-    fn parse(operator: &str, operands: &str) -> Result<Calculation, CalculationParseError> {
-        let operator = match operator.trim().to_lowercase().as_str() {
-            OPERATOR_ADD => Operator::Add,
-            OPERATOR_SUB => Operator::Sub,
-            OPERATOR_MUL => Operator::Mul,
-            OPERATOR_DIV => Operator::Div,
-            OPERATOR_REM => Operator::Rem,
-            _ => return Err(CalculationParseError::new(format!("unknown operator: {}", operator)))
+    fn parse(operator: &str, operands: &str, mode: &str) -> Result<Calculation, CalculationParseError> {
+        let mode = if mode.trim().is_empty() { MODE_FLAT } else { mode.trim() };
+        return match mode.to_lowercase().as_str() {
+            MODE_FLAT => Calculation::parse_flat(operator, operands),
+            MODE_POSTFIX => Calculation::parse_postfix(operands),
+            _ => Err(CalculationParseError::new(format!("unknown mode: {}", mode))),
+        };
+    }
+
+    fn from_operands(operator: Operator, operands: Vec<f64>) -> Calculation {
+        return Calculation {
+            mode: CalculationMode::Flat { operator: operator, operands: operands },
+        };
+    }
+
+    fn parse_flat(operator: &str, operands: &str) -> Result<Calculation, CalculationParseError> {
+        let operator = match Operator::parse(operator) {
+            Ok(v) => v,
+            Err(v) => return Err(v),
         };
         let mut ops = Vec::new();
         for t in operands.trim().split(",") {
@@ -345,11 +581,69 @@ impl Calculation {
             };
             ops.push(v);
         }
-        return Ok(Calculation{
-            operator: operator,
-            operands: ops,
+        return Ok(Calculation::from_operands(operator, ops));
+    }
+
+    // parse_postfix tokenizes a comma-separated Reverse Polish Notation
+    // expression, e.g. "2,3,add,4,mul" for `(2 + 3) * 4`, into a mix of
+    // operand and operator tokens consumed left to right by `evaluate`.
+    fn parse_postfix(operands: &str) -> Result<Calculation, CalculationParseError> {
+        let mut tokens = Vec::new();
+        for t in operands.trim().split(",") {
+            let t = t.trim();
+            match Operator::parse(t) {
+                Ok(op) => tokens.push(PostfixToken::Operator(op)),
+                Err(_) => {
+                    let v: f64 = match t.parse() {
+                        Ok(v) => v,
+                        Err(v) => return Err(CalculationParseError::new(format!("{}: {}", v, operands)))
+                    };
+                    tokens.push(PostfixToken::Operand(v));
+                },
+            }
+        }
+        return Ok(Calculation {
+            mode: CalculationMode::Postfix { tokens: tokens },
         });
     }
+
+    // evaluate returns the result of the calculation, or `None` when a flat
+    // calculation has no operands to reduce over (preserved for backward
+    // compatibility: the server responds "nan" in that case rather than
+    // erroring). A malformed postfix expression is always an error.
+    fn evaluate(self) -> Result<Option<f64>, CalculationParseError> {
+        return match self.mode {
+            CalculationMode::Flat { operator, operands } => {
+                Ok(operands.into_iter().reduce(|a, b| operator.apply(a, b)))
+            },
+            CalculationMode::Postfix { tokens } => {
+                let mut stack: Vec<f64> = Vec::new();
+                for token in tokens {
+                    match token {
+                        PostfixToken::Operand(v) => stack.push(v),
+                        PostfixToken::Operator(op) => {
+                            let b = match stack.pop() {
+                                Some(v) => v,
+                                None => return Err(CalculationParseError::new(String::from(
+                                    "operator with fewer than two operands on the stack"))),
+                            };
+                            let a = match stack.pop() {
+                                Some(v) => v,
+                                None => return Err(CalculationParseError::new(String::from(
+                                    "operator with fewer than two operands on the stack"))),
+                            };
+                            stack.push(op.apply(a, b));
+                        },
+                    }
+                }
+                if stack.len() != 1 {
+                    return Err(CalculationParseError::new(format!(
+                        "expression did not reduce to a single value: stack={:?}", stack)));
+                }
+                Ok(Some(stack[0]))
+            },
+        };
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::time;
+
+const MAX_HEADER_BYTES: usize = 8192;
+const READ_CHUNK_BYTES: usize = 512;
+
+pub(crate) struct HttpRequest {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: String,
+}
+
+impl HttpRequest {
+    pub(crate) fn keep_alive(&self) -> bool {
+        match self.headers.get("connection") {
+            Some(v) => v.trim().to_lowercase() != "close",
+            None => true,
+        }
+    }
+}
+
+// read_request reads a single request off `conn`. `idle_timeout` bounds how
+// long the server will wait for the client to send the first byte of a new
+// request (the keep-alive idle window); once that byte arrives,
+// `request_timeout` bounds how long it has to finish sending the full
+// request headers and body. `carry` holds any bytes read past the end of
+// the previous request on this same connection (a pipelined next request
+// line can arrive in the same TCP segment as the current body); callers
+// reuse the same `carry` buffer across calls for the life of the
+// connection so those bytes aren't lost.
+pub(crate) async fn read_request(
+    conn: &mut TcpStream,
+    idle_timeout: Duration,
+    request_timeout: Duration,
+    carry: &mut Vec<u8>,
+) -> Result<HttpRequest, RequestError> {
+    let mut buf = mem::take(carry);
+    let mut chunk = [0; READ_CHUNK_BYTES];
+    // Bytes carried over from the previous request are already part of this
+    // one, so `request_timeout` applies from the start.
+    let mut deadline: Option<Instant> = if buf.is_empty() {
+        None
+    } else {
+        Some(Instant::now() + request_timeout)
+    };
+
+    let header_end = loop {
+        if let Some(i) = find_header_end(&buf) {
+            break i;
+        }
+        if buf.len() > MAX_HEADER_BYTES {
+            return Err(RequestError::Parse(RequestParseError::new(
+                String::from("request headers too large"))));
+        }
+        let n = read_with_deadline(conn, &mut chunk, &mut deadline, idle_timeout, request_timeout).await?;
+        if n == 0 {
+            if buf.is_empty() {
+                // No bytes of a new request have arrived yet: the peer
+                // closed an idle keep-alive connection, not a mid-request
+                // disconnect. That's the normal way a keep-alive
+                // connection ends, not a malformed request.
+                return Err(RequestError::Closed);
+            }
+            return Err(RequestError::Parse(RequestParseError::new(String::from(
+                "connection closed before headers were fully received"))));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let request_line = match lines.next() {
+        Some(v) => v,
+        None => return Err(RequestError::Parse(RequestParseError::new(
+            String::from("missing request line")))),
+    };
+    let mut request_line_parts = request_line.split_whitespace();
+    let method = match request_line_parts.next() {
+        Some(v) => v.to_string(),
+        None => return Err(RequestError::Parse(RequestParseError::new(
+            String::from("missing request method")))),
+    };
+    let path = match request_line_parts.next() {
+        Some(v) => v.to_string(),
+        None => return Err(RequestError::Parse(RequestParseError::new(
+            String::from("missing request path")))),
+    };
+
+    let mut headers = HashMap::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut kv = line.splitn(2, ':');
+        let key = match kv.next() {
+            Some(v) => v.trim().to_lowercase(),
+            None => continue,
+        };
+        let value = kv.next().unwrap_or("").trim().to_string();
+        headers.insert(key, value);
+    }
+
+    let content_length: usize = match headers.get("content-length") {
+        Some(v) => match v.trim().parse() {
+            Ok(v) => v,
+            Err(v) => return Err(RequestError::Parse(RequestParseError::new(format!(
+                "invalid content-length header: {}", v)))),
+        },
+        None => 0,
+    };
+
+    let mut body = buf.split_off(header_end + 4);
+    while body.len() < content_length {
+        let n = read_with_deadline(conn, &mut chunk, &mut deadline, idle_timeout, request_timeout).await?;
+        if n == 0 {
+            return Err(RequestError::Parse(RequestParseError::new(String::from(
+                "connection closed before request body was fully received"))));
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    *carry = body.split_off(content_length);
+
+    return Ok(HttpRequest {
+        method: method,
+        path: path,
+        headers: headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    });
+}
+
+// read_exact_with_deadline fills `buf` completely, bounding the whole
+// operation the same way `read_with_deadline` bounds a single read: callers
+// (e.g. `fastcgi::read_request`) share one `deadline` across a request so a
+// stalled peer can't hold a connection (and the `Semaphore` permit guarding
+// it) open indefinitely.
+pub(crate) async fn read_exact_with_deadline(
+    conn: &mut TcpStream,
+    buf: &mut [u8],
+    deadline: &mut Option<Instant>,
+    idle_timeout: Duration,
+    request_timeout: Duration,
+) -> Result<(), RequestError> {
+    let mut read = 0;
+    while read < buf.len() {
+        let n = read_with_deadline(conn, &mut buf[read..], deadline, idle_timeout, request_timeout).await?;
+        if n == 0 {
+            return Err(RequestError::Parse(RequestParseError::new(String::from(
+                "connection closed before expected bytes were fully received"))));
+        }
+        read += n;
+    }
+    return Ok(());
+}
+
+// read_with_deadline performs a single read, bounding it by the remaining
+// budget: `idle_timeout` before any bytes of the request have arrived,
+// `request_timeout` (counted from the first byte) after that.
+async fn read_with_deadline(
+    conn: &mut TcpStream,
+    chunk: &mut [u8],
+    deadline: &mut Option<Instant>,
+    idle_timeout: Duration,
+    request_timeout: Duration,
+) -> Result<usize, RequestError> {
+    let remaining = match deadline {
+        Some(d) => {
+            let now = Instant::now();
+            if now >= *d {
+                return Err(RequestError::Timeout);
+            }
+            *d - now
+        },
+        None => idle_timeout,
+    };
+    let n = match time::timeout(remaining, conn.read(chunk)).await {
+        Ok(Ok(v)) => v,
+        Ok(Err(v)) => return Err(RequestError::Parse(RequestParseError::new(v.to_string()))),
+        Err(_) => return Err(RequestError::Timeout),
+    };
+    if deadline.is_none() {
+        *deadline = Some(Instant::now() + request_timeout);
+    }
+    return Ok(n);
+}
+
+fn find_header_end(buf: &[u8]) -> Option<usize> {
+    return buf.windows(4).position(|w| w == b"\r\n\r\n");
+}
+
+pub(crate) enum RequestError {
+    Parse(RequestParseError),
+    Timeout,
+    // Closed means the peer closed the connection before sending any bytes
+    // of a new request — a normal keep-alive disconnect, not a malformed
+    // request.
+    Closed,
+}
+
+#[derive(Debug)]
+pub(crate) struct RequestParseError {
+    msg: String,
+}
+
+impl RequestParseError {
+    pub(crate) fn new(msg: String) -> RequestParseError {
+        return RequestParseError { msg };
+    }
+}
+
+impl fmt::Display for RequestParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "failed to parse request: {}", self.msg);
+    }
+}
+
+impl Error for RequestParseError {
+    fn description(&self) -> &str {
+        return &self.msg;
+    }
+}